@@ -1,14 +1,39 @@
-use std::{time::Instant};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use deadpool_postgres::{Config as PgConfig, Pool, Runtime};
 use serde::{Deserialize, Serialize};
 use tokio::net::{TcpListener};
-use tonic::transport::Channel;
-use tonic::Request;
-use axum::{Json, Router, extract::{State}, routing::{get, post}};
-use orderbook_proto::{CancelOrderRequest, CancelOrderResponse, ModifyOrderRequest, ModifyOrderResponse, NewOrderRequest, NewOrderResponse, OrderBookClient, orders::OrderType, BookRequest};
-use prometheus::{HistogramOpts, HistogramVec, IntCounter, Registry, TextEncoder};
+use tokio::sync::{broadcast, mpsc};
+use tokio_postgres::NoTls;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status, Streaming};
+use axum::{
+    Json, Router,
+    extract::{Query, State, ws::{Message, WebSocket, WebSocketUpgrade}},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use orderbook_proto::{CancelOrderRequest, CancelOrderResponse, ModifyOrderRequest, ModifyOrderResponse, NewOrderRequest, NewOrderResponse, OrderBookClient, orders::OrderType, BookRequest, BookDepthUpdate, StreamOrderEventsRequest};
+use prometheus::{HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
 use lazy_static::lazy_static;
 
+/// Capacity of the broadcast channel fanning order lifecycle events out to `/ws` subscribers.
+const ORDER_EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Raw proto prices are scaled integers (fixed-point, 1/100); divide by this before writing
+/// to Postgres so the `fill_events` table holds a UI-scaled decimal instead of the wire
+/// representation. Quantity is a plain lot/share count and is never scaled.
+const PRICE_SCALE : f64 = 100.0;
+
+/// Default backend, used when `ORDERBOOK_BACKENDS` isn't set.
+const DEFAULT_ORDERBOOK_BACKEND : &str = "http://[::1]:50051";
+
+/// Bounded retry budget for a single RPC before the handler gives up and returns 503.
+const MAX_GRPC_ATTEMPTS : u32 = 3;
+const GRPC_RETRY_BASE_DELAY : Duration = Duration::from_millis(50);
+
 lazy_static!(
     static ref NEW_ORDER_TOTAL_DURATION : HistogramVec = HistogramVec::new(
         HistogramOpts::new(
@@ -43,22 +68,448 @@ lazy_static!(
     ).unwrap();
 
     static ref REQUEST_COUNTER : IntCounter = IntCounter::new("request_counter", "total no of requests").unwrap();
+
+    static ref NEW_ORDER_GRPC_DURATION : HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "new_order_grpc_duration_ms",
+            "time spent in the new order grpc round-trip only")
+        .buckets(vec![1.0, 5.0, 10.0, 25.0, 50.0, 100.0]),
+        &["order-type", "status"]
+    ).unwrap();
+
+    static ref CANCEL_ORDER_GRPC_DURATION : HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "cancel_order_grpc_duration_ms",
+            "time spent in the cancel order grpc round-trip only")
+        .buckets(vec![1.0, 5.0, 10.0, 25.0, 50.0, 100.0]),
+        &["status"]
+    ).unwrap();
+
+    static ref MODIFY_ORDER_GRPC_DURATION : HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "modify_order_grpc_duration_ms",
+            "time spent in the modify order grpc round-trip only")
+        .buckets(vec![1.0, 5.0, 10.0, 25.0, 50.0, 100.0]),
+        &["status"]
+    ).unwrap();
+
+    static ref DEPTH_GRPC_DURATION : HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "depth_grpc_duration_ms",
+            "time spent in the depth grpc round-trip only")
+        .buckets(vec![1.0, 5.0, 10.0, 25.0, 50.0, 100.0]),
+        &["asset_name", "status"]
+    ).unwrap();
+
+    static ref ERROR_COUNTER : IntCounterVec = IntCounterVec::new(
+        Opts::new("request_errors_total", "total number of request errors by endpoint and cause"),
+        &["endpoint", "cause"]
+    ).unwrap();
+
+    static ref IN_FLIGHT_REQUESTS : IntGaugeVec = IntGaugeVec::new(
+        Opts::new("in_flight_requests", "number of requests currently being handled, by endpoint"),
+        &["endpoint"]
+    ).unwrap();
 );
 
+/// Decrements the in-flight gauge for `endpoint` when dropped, so every handler
+/// return path (including early validation returns) keeps the gauge accurate.
+struct InFlightGuard{
+    endpoint : &'static str
+}
+
+impl InFlightGuard{
+    fn new(endpoint : &'static str) -> Self{
+        IN_FLIGHT_REQUESTS.with_label_values(&[endpoint]).inc();
+        Self { endpoint }
+    }
+}
+
+impl Drop for InFlightGuard{
+    fn drop(&mut self){
+        IN_FLIGHT_REQUESTS.with_label_values(&[self.endpoint]).dec();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SharedState{
     pub client : OrderBookClient<Channel>,
-    registry : Registry
+    registry : Registry,
+    pub order_events : broadcast::Sender<OrderEvent>,
+    pub fills : mpsc::UnboundedSender<FillEvent>,
+    /// `None` when `NATS_URL` isn't set, so publishing is a no-op instead of required infra.
+    /// Also `None` while a configured NATS server hasn't connected yet, filled in once
+    /// `connect_nats_in_background` succeeds, so a down message bus can't block startup.
+    pub nats_client : Arc<Mutex<Option<async_nats::Client>>>,
+    /// `order_id -> security_name`, populated by `new_order` so `modify_order`/`cancel_order`
+    /// (whose requests don't carry a security name) can still route NATS events correctly.
+    order_securities : Arc<Mutex<HashMap<String, String>>>
 }
 
 impl SharedState {
     pub async fn new() -> Result<Self, anyhow::Error>{
-        let client = OrderBookClient::connect("http://[::1]:50051").await?;
+        let client = OrderBookClient::new(build_orderbook_channel()?);
         let registry = Registry::new();
-        Ok(Self { client, registry })
+        register_metrics(&registry)?;
+        let (order_events, _) = broadcast::channel(ORDER_EVENT_CHANNEL_CAPACITY);
+        tokio::spawn(stream_backend_order_events(client.clone(), order_events.clone()));
+
+        let pg_pool = build_pg_pool()?;
+        let (fills, fill_rx) = mpsc::unbounded_channel::<FillEvent>();
+        tokio::spawn(persist_fill_events(pg_pool, fill_rx));
+
+        let nats_client = Arc::new(Mutex::new(None));
+        let order_securities = Arc::new(Mutex::new(HashMap::new()));
+        if let Ok(url) = std::env::var("NATS_URL"){
+            tokio::spawn(connect_nats_in_background(url, nats_client.clone()));
+        }
+
+        Ok(Self { client, registry, order_events, fills, nats_client, order_securities })
+    }
+}
+
+/// Connects to NATS with backoff and swaps the client in once it succeeds, so a
+/// configured-but-unreachable NATS server delays publishing instead of failing startup
+/// (mirrors chunk0-5's treatment of the orderbook backend connection).
+async fn connect_nats_in_background(url : String, nats_client : Arc<Mutex<Option<async_nats::Client>>>){
+    let mut delay = GRPC_RETRY_BASE_DELAY;
+    loop {
+        match async_nats::connect(&url).await{
+            Ok(client) => {
+                *nats_client.lock().unwrap() = Some(client);
+                return;
+            }
+            Err(err) => {
+                eprintln!("nats connect to {url} failed, retrying in {delay:?}: {err}");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(30));
+            }
+        }
     }
 }
 
+/// Subscribes to the backend's order lifecycle stream so `/ws` also surfaces events
+/// `new_order`/`modify_order`/`cancel_order` would never see on their own — e.g. another
+/// client's marketable order filling against one of our resting orders. Reconnects with
+/// backoff on a dropped stream, mirroring `connect_nats_in_background`.
+async fn stream_backend_order_events(mut client : OrderBookClient<Channel>, order_events : broadcast::Sender<OrderEvent>){
+    let mut delay = GRPC_RETRY_BASE_DELAY;
+    loop {
+        let mut stream : Streaming<_> = match client.stream_order_events(Request::new(StreamOrderEventsRequest{})).await{
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                eprintln!("order event stream failed to start, retrying in {delay:?}: {status}");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(30));
+                continue;
+            }
+        };
+        delay = GRPC_RETRY_BASE_DELAY;
+
+        loop {
+            match stream.message().await{
+                Ok(Some(event)) => {
+                    let Some(event_kind) = order_event_kind_from_i32(event.event_kind) else { continue };
+                    let _ = order_events.send(OrderEvent{
+                        event_kind,
+                        order_id : event.order_id,
+                        security_name : Some(event.security_name),
+                        side : Some(if event.is_buy_side {BookSide::Bid} else {BookSide::Ask}),
+                        delta_price : event.price,
+                        delta_quantity : event.quantity,
+                        resting_quantity : event.resting_quantity,
+                        status : event.status
+                    });
+                }
+                Ok(None) => break,
+                Err(status) => {
+                    eprintln!("order event stream errored, reconnecting: {status}");
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn order_event_kind_from_i32(value : i32) -> Option<OrderEventKind>{
+    match value{
+        0 => Some(OrderEventKind::New),
+        1 => Some(OrderEventKind::Modify),
+        2 => Some(OrderEventKind::Cancel),
+        _ => None
+    }
+}
+
+/// Looks up the security name for an order previously seen by `new_order`, falling
+/// back to `"unknown"` for orders this process never observed (e.g. a restart).
+fn security_name_for_order(shared_state : &SharedState, order_id : &str) -> String{
+    shared_state.order_securities.lock().unwrap()
+        .get(order_id)
+        .cloned()
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Fire-and-forget publish of an order event to `orders.{security_name}.{event_kind}`.
+/// Runs on its own task so a slow/unreachable NATS server never adds to request latency.
+fn publish_order_event(shared_state : &SharedState, security_name : &str, event_kind : &str, payload : NatsOrderEvent){
+    let Some(client) = shared_state.nats_client.lock().unwrap().clone() else { return };
+    let subject = format!("orders.{security_name}.{event_kind}");
+    tokio::spawn(async move {
+        let Ok(body) = serde_json::to_vec(&payload) else { return };
+        if let Err(err) = client.publish(subject, body.into()).await{
+            eprintln!("failed to publish order event to nats: {err}");
+        }
+    });
+}
+
+#[derive(Debug, Serialize)]
+struct NatsOrderEvent{
+    order_id : String,
+    side : Option<&'static str>,
+    price : Option<u32>,
+    quantity : Option<u32>,
+    status : u32
+}
+
+/// Builds a load-balanced, lazily-connecting channel over every backend in
+/// `ORDERBOOK_BACKENDS` (comma-separated), falling back to [`DEFAULT_ORDERBOOK_BACKEND`].
+/// Individual backends that are down are skipped transparently by the balancer and
+/// retried in the background, instead of failing the whole client up front.
+fn build_orderbook_channel() -> Result<Channel, anyhow::Error>{
+    let backends = std::env::var("ORDERBOOK_BACKENDS").unwrap_or_else(|_| DEFAULT_ORDERBOOK_BACKEND.to_string());
+    let endpoints = backends
+        .split(',')
+        .map(|addr| Endpoint::from_shared(addr.trim().to_string()))
+        .collect::<Result<Vec<Endpoint>, _>>()?;
+    Ok(Channel::balance_list(endpoints.into_iter()))
+}
+
+/// Retries `call` with exponential backoff while the backend reports a transient
+/// error, up to [`MAX_GRPC_ATTEMPTS`]. Returns the last error once the budget is spent.
+async fn call_with_retry<T, F, Fut>(mut call : F) -> Result<T, Status>
+where
+    F : FnMut() -> Fut,
+    Fut : std::future::Future<Output = Result<T, Status>>
+{
+    let mut attempt = 0;
+    loop {
+        match call().await{
+            Ok(value) => return Ok(value),
+            Err(status) if attempt + 1 < MAX_GRPC_ATTEMPTS && is_retryable(&status) => {
+                attempt += 1;
+                tokio::time::sleep(GRPC_RETRY_BASE_DELAY * 2u32.pow(attempt)).await;
+            }
+            Err(status) => return Err(status),
+        }
+    }
+}
+
+fn is_retryable(status : &Status) -> bool{
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded | Code::Aborted)
+}
+
+fn register_metrics(registry : &Registry) -> Result<(), anyhow::Error>{
+    registry.register(Box::new(NEW_ORDER_TOTAL_DURATION.clone()))?;
+    registry.register(Box::new(NEW_ORDER_GRPC_DURATION.clone()))?;
+    registry.register(Box::new(CANCEL_ORDER_TOTAL_DURATION.clone()))?;
+    registry.register(Box::new(CANCEL_ORDER_GRPC_DURATION.clone()))?;
+    registry.register(Box::new(MODIFY_ORDER_TOTAL_DURATION.clone()))?;
+    registry.register(Box::new(MODIFY_ORDER_GRPC_DURATION.clone()))?;
+    registry.register(Box::new(DEPTH_TOTAL_DURATION.clone()))?;
+    registry.register(Box::new(DEPTH_GRPC_DURATION.clone()))?;
+    registry.register(Box::new(REQUEST_COUNTER.clone()))?;
+    registry.register(Box::new(ERROR_COUNTER.clone()))?;
+    registry.register(Box::new(IN_FLIGHT_REQUESTS.clone()))?;
+    Ok(())
+}
+
+/// One row per `new_order`/`modify_order`/`cancel_order` call (event kind new, modify or
+/// cancel), written to Postgres through a background task so the HTTP hot path never
+/// blocks on the DB. Fills are not written as rows of their own.
+#[derive(Debug, Clone, Serialize)]
+pub struct FillEvent{
+    pub order_id : String,
+    pub security_name : Option<String>,
+    pub side : Option<BookSide>,
+    pub price : Option<f64>,
+    pub quantity : Option<f64>,
+    pub counterparty_order_index : Option<u32>,
+    pub event_kind : OrderEventKind,
+    pub timestamp_ms : i64
+}
+
+impl FillEvent{
+    fn now_ms() -> i64{
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+    }
+}
+
+/// Converts a raw wire price into the UI-scaled decimal stored in `fill_events`.
+/// Quantity is a plain lot/share count and must never go through this.
+fn scale_price(raw : u32) -> f64{
+    raw as f64 / PRICE_SCALE
+}
+
+/// `level_count = 0` means "unlimited" on the wire; every endpoint that forwards it
+/// into a `BookRequest` goes through here so `/depth` and `/depth/stream` agree.
+fn normalize_level_count(level_count : Option<u32>) -> Option<u32>{
+    level_count.filter(|&count| count != 0)
+}
+
+fn build_pg_pool() -> Result<Pool, anyhow::Error>{
+    let mut cfg = PgConfig::new();
+    cfg.host = Some(std::env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string()));
+    cfg.dbname = Some(std::env::var("PG_DBNAME").unwrap_or_else(|_| "trading".to_string()));
+    cfg.user = std::env::var("PG_USER").ok();
+    cfg.password = std::env::var("PG_PASSWORD").ok();
+    let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
+    Ok(pool)
+}
+
+async fn persist_fill_events(pool : Pool, mut fill_rx : mpsc::UnboundedReceiver<FillEvent>){
+    while let Some(event) = fill_rx.recv().await{
+        if let Err(err) = insert_fill_event(&pool, &event).await{
+            eprintln!("failed to persist fill event for order {}: {err}", event.order_id);
+        }
+    }
+}
+
+async fn insert_fill_event(pool : &Pool, event : &FillEvent) -> Result<(), anyhow::Error>{
+    let client = pool.get().await?;
+    let side = event.side.map(|s| match s { BookSide::Bid => "bid", BookSide::Ask => "ask" });
+    client.execute(
+        "INSERT INTO fill_events \
+            (order_id, security_name, side, price, quantity, counterparty_order_index, event_kind, occurred_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, to_timestamp($8 / 1000.0))",
+        &[
+            &event.order_id,
+            &event.security_name,
+            &side,
+            &event.price,
+            &event.quantity,
+            &event.counterparty_order_index.map(|v| v as i32),
+            &format!("{:?}", event.event_kind).to_lowercase(),
+            &event.timestamp_ms
+        ]
+    ).await?;
+    Ok(())
+}
+
+/// Which book side an order event's resting quantity belongs to.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSide{
+    Bid,
+    Ask
+}
+
+/// Which lifecycle stage an order event describes.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventKind{
+    New,
+    Modify,
+    Cancel
+}
+
+/// How long a gap in the sequence can persist before [`DepthReorderBuffer`] gives up
+/// and asks the caller to re-request a fresh snapshot instead of waiting forever.
+const DEPTH_REORDER_WINDOW : Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthLevel{
+    pub price : u32,
+    pub quantity : u32
+}
+
+/// A full book snapshot, tagged with the sequence number clients should start
+/// applying diffs from.
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthSnapshot{
+    pub sequence : u64,
+    pub security_name : String,
+    pub ask_depth : Vec<DepthLevel>,
+    pub bid_depth : Vec<DepthLevel>
+}
+
+/// A single price level changing: the new aggregate quantity at `price`, or
+/// `removed = true` once it drops to zero.
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthDiff{
+    pub sequence : u64,
+    pub side : BookSide,
+    pub price : u32,
+    pub quantity : u32,
+    pub removed : bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DepthStreamMessage{
+    Snapshot(DepthSnapshot),
+    Diff(DepthDiff)
+}
+
+/// Applies depth diffs strictly in sequence order even if they arrive out of order.
+/// Diffs that skip ahead are held in `pending` until the gap fills; if it doesn't
+/// fill within [`DEPTH_REORDER_WINDOW`], `ingest` returns `None` so the caller can
+/// discard the buffer and re-request a fresh snapshot. Used by `stream_depth_diffs`
+/// to survive out-of-order delivery from the backend's push feed.
+struct DepthReorderBuffer{
+    last_applied : u64,
+    pending : BTreeMap<u64, DepthDiff>,
+    gap_opened_at : Option<Instant>
+}
+
+impl DepthReorderBuffer{
+    fn new(initial_sequence : u64) -> Self{
+        Self { last_applied : initial_sequence, pending : BTreeMap::new(), gap_opened_at : None }
+    }
+
+    fn ingest(&mut self, diff : DepthDiff) -> Option<Vec<DepthDiff>>{
+        if diff.sequence <= self.last_applied{
+            return Some(Vec::new());
+        }
+        self.pending.insert(diff.sequence, diff);
+        let gap_opened_at = *self.gap_opened_at.get_or_insert_with(Instant::now);
+        if gap_opened_at.elapsed() > DEPTH_REORDER_WINDOW && !self.pending.contains_key(&(self.last_applied + 1)){
+            self.pending.clear();
+            self.gap_opened_at = None;
+            return None;
+        }
+
+        let mut ready = Vec::new();
+        while let Some(next) = self.pending.remove(&(self.last_applied + 1)){
+            self.last_applied = next.sequence;
+            ready.push(next);
+        }
+        if self.pending.is_empty(){
+            self.gap_opened_at = None;
+        }
+        Some(ready)
+    }
+}
+
+/// Broadcast to every `/ws` subscriber whenever `new_order`, `modify_order` or
+/// `cancel_order` completes, or whenever the backend pushes a lifecycle event of its
+/// own over `stream_backend_order_events` (e.g. a fill against our resting order):
+/// the incremental change plus a snapshot of the resulting resting state, so clients
+/// don't have to poll `/depth`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderEvent{
+    pub event_kind : OrderEventKind,
+    pub order_id : String,
+    /// For modify/cancel events, whose requests don't carry the security name, this is the
+    /// name recorded by `new_order` for the order, or `"unknown"` if none was recorded.
+    pub security_name : Option<String>,
+    pub side : Option<BookSide>,
+    pub delta_price : Option<u32>,
+    pub delta_quantity : Option<u32>,
+    pub resting_quantity : Option<u32>,
+    pub status : u32
+}
+
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
 
@@ -69,6 +520,8 @@ async fn main() -> Result<(), anyhow::Error> {
     .route("/cancel", post(cancel_order))
     .route("/depth", get(depth))
     .route("/metric", get(metric))
+    .route("/ws", get(ws_handler))
+    .route("/depth/stream", get(depth_stream_handler))
     .with_state(shared_state);
     
     let listener = TcpListener::bind("127.0.0.1:8000").await.unwrap();
@@ -79,61 +532,228 @@ async fn main() -> Result<(), anyhow::Error> {
 async fn new_order(
     State(mut shared_state) : State<SharedState>, // axum clones the client instance for us over here.
     Json(request) : Json<NewOrder>, ) -> Json<NewOrderRes>{
+        let _in_flight = InFlightGuard::new("new_order");
         let start_time = Instant::now();
         let req = request;
-        let order_request = Request::new(NewOrderRequest{
+        if req.order_type.requires_price() && req.price.is_none(){
+            ERROR_COUNTER.with_label_values(&["new_order", "missing_price"]).inc();
+            return Json(NewOrderRes{
+                order_id : String::new(),
+                status : 400,
+                order_index : None,
+                cause : Some(format!("price is required for order_type {}", req.order_type.metric_label()))
+            });
+        }
+        let security_name = req.security_name.clone();
+        let new_order_request = NewOrderRequest{
             user_id : None,
             price : req.price,
             quantity : req.quantity,
             is_buy_side : req.is_buy_side,
-            security_name : req.security_name,
-            order_type : OrderType::Market as i32
-        });
-        
-        let response = shared_state.client.new_order(order_request).await.unwrap().into_inner();
+            security_name : req.security_name.clone(),
+            order_type : OrderType::from(req.order_type) as i32
+        };
+
+        let grpc_start = Instant::now();
+        let grpc_result = call_with_retry(|| {
+            let mut client = shared_state.client.clone();
+            let message = new_order_request.clone();
+            async move { client.new_order(Request::new(message)).await }
+        }).await;
+        let grpc_duration = grpc_start.elapsed().as_millis() as f64;
+        let response = match grpc_result{
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                NEW_ORDER_GRPC_DURATION.with_label_values(&[req.order_type.metric_label().to_string(), "error".to_string()]).observe(grpc_duration);
+                ERROR_COUNTER.with_label_values(&["new_order", "backend_unavailable"]).inc();
+                return Json(NewOrderRes{
+                    order_id : String::new(),
+                    status : 503,
+                    order_index : None,
+                    cause : Some(format!("orderbook backend unavailable: {}", status.message()))
+                });
+            }
+        };
         let res_to_send = NewOrderRes::from(response);
+        NEW_ORDER_GRPC_DURATION.with_label_values(&[req.order_type.metric_label().to_string(), res_to_send.status.to_string()]).observe(grpc_duration);
+        if res_to_send.status != 200{
+            ERROR_COUNTER.with_label_values(&["new_order", "rejected"]).inc();
+        }
         let total_duration = start_time.elapsed().as_millis() as f64;
-        NEW_ORDER_TOTAL_DURATION.with_label_values(&["New Order", "success"]).observe(total_duration);
+        NEW_ORDER_TOTAL_DURATION.with_label_values(&[req.order_type.metric_label().to_string(), res_to_send.status.to_string()]).observe(total_duration);
         REQUEST_COUNTER.inc();
+        let _ = shared_state.order_events.send(OrderEvent{
+            event_kind : OrderEventKind::New,
+            order_id : res_to_send.order_id.clone(),
+            security_name : Some(security_name.clone()),
+            side : Some(if req.is_buy_side {BookSide::Bid} else {BookSide::Ask}),
+            delta_price : req.price,
+            delta_quantity : Some(req.quantity),
+            resting_quantity : res_to_send.order_index.map(|_| req.quantity),
+            status : res_to_send.status
+        });
+        let _ = shared_state.fills.send(FillEvent{
+            order_id : res_to_send.order_id.clone(),
+            security_name : Some(security_name.clone()),
+            side : Some(if req.is_buy_side {BookSide::Bid} else {BookSide::Ask}),
+            price : req.price.map(scale_price),
+            quantity : Some(req.quantity as f64),
+            counterparty_order_index : res_to_send.order_index,
+            event_kind : OrderEventKind::New,
+            timestamp_ms : FillEvent::now_ms()
+        });
+        shared_state.order_securities.lock().unwrap().insert(res_to_send.order_id.clone(), security_name.clone());
+        publish_order_event(&shared_state, &security_name, "new", NatsOrderEvent{
+            order_id : res_to_send.order_id.clone(),
+            side : Some(if req.is_buy_side {"bid"} else {"ask"}),
+            price : req.price,
+            quantity : Some(req.quantity),
+            status : res_to_send.status
+        });
         Json(res_to_send)
 }
 
 async fn modify_order(
     State(mut shared_state) : State<SharedState>, 
     Json(request) : Json<ModifyOrder>) -> Json<ModifyOrderRes>{
+        let _in_flight = InFlightGuard::new("modify_order");
         let start_time = Instant::now();
-       
+
         let req = request;
         let new_price = if req.new_price.unwrap() == 0 {None} else { req.new_price};
         let new_quantity = if req.new_quantity.unwrap() == 0 {None} else { req.new_quantity};
 
-        let modify_request = Request::new(ModifyOrderRequest {
-            order_id : req.order_id,
+        let modify_order_request = ModifyOrderRequest {
+            order_id : req.order_id.clone(),
             new_price,
             new_quantity,
             side : req.is_buy_side
-        });
-    let response = shared_state.client.modify_order(modify_request).await.unwrap().into_inner();
+        };
+    let grpc_start = Instant::now();
+    let grpc_result = call_with_retry(|| {
+        let mut client = shared_state.client.clone();
+        let message = modify_order_request.clone();
+        async move { client.modify_order(Request::new(message)).await }
+    }).await;
+    let grpc_duration = grpc_start.elapsed().as_millis() as f64;
+    let response = match grpc_result{
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            MODIFY_ORDER_GRPC_DURATION.with_label_values(&["error"]).observe(grpc_duration);
+            ERROR_COUNTER.with_label_values(&["modify_order", "backend_unavailable"]).inc();
+            return Json(ModifyOrderRes{
+                order_id : req.order_id,
+                status : 503,
+                output : Some(format!("orderbook backend unavailable: {}", status.message()))
+            });
+        }
+    };
     let converted_res = ModifyOrderRes::from(response);
+    MODIFY_ORDER_GRPC_DURATION.with_label_values(&[converted_res.status.to_string()]).observe(grpc_duration);
+    if converted_res.status != 200{
+        ERROR_COUNTER.with_label_values(&["modify_order", "rejected"]).inc();
+    }
     let total_time = start_time.elapsed().as_millis() as f64;
     MODIFY_ORDER_TOTAL_DURATION.with_label_values(&[converted_res.status.to_string()]).observe(total_time);
     REQUEST_COUNTER.inc();
-    Json(converted_res) 
+    let security_name = security_name_for_order(&shared_state, &converted_res.order_id);
+    let _ = shared_state.order_events.send(OrderEvent{
+        event_kind : OrderEventKind::Modify,
+        order_id : converted_res.order_id.clone(),
+        security_name : Some(security_name.clone()),
+        side : Some(if req.is_buy_side {BookSide::Bid} else {BookSide::Ask}),
+        delta_price : new_price,
+        delta_quantity : new_quantity,
+        resting_quantity : new_quantity,
+        status : converted_res.status
+    });
+    let _ = shared_state.fills.send(FillEvent{
+        order_id : converted_res.order_id.clone(),
+        security_name : Some(security_name.clone()),
+        side : Some(if req.is_buy_side {BookSide::Bid} else {BookSide::Ask}),
+        price : new_price.map(scale_price),
+        quantity : new_quantity.map(|q| q as f64),
+        counterparty_order_index : None,
+        event_kind : OrderEventKind::Modify,
+        timestamp_ms : FillEvent::now_ms()
+    });
+    publish_order_event(&shared_state, &security_name, "modify", NatsOrderEvent{
+        order_id : converted_res.order_id.clone(),
+        side : Some(if req.is_buy_side {"bid"} else {"ask"}),
+        price : new_price,
+        quantity : new_quantity,
+        status : converted_res.status
+    });
+    Json(converted_res)
 
 }
 async fn cancel_order(
     State(mut shared_state) : State<SharedState>,
     Json(request) : Json<CancelOrder>) -> Json<CancelOrderRes> {
+        let _in_flight = InFlightGuard::new("cancel_order");
         let start_time = Instant::now();
         let req = request;
-        let cancel_request = Request::new(CancelOrderRequest{
-            order_id : req.order_id
-        });
-    let response = shared_state.client.cancel_order(cancel_request).await.unwrap().into_inner();
+        let cancel_order_request = CancelOrderRequest{
+            order_id : req.order_id.clone()
+        };
+    let grpc_start = Instant::now();
+    let grpc_result = call_with_retry(|| {
+        let mut client = shared_state.client.clone();
+        let message = cancel_order_request.clone();
+        async move { client.cancel_order(Request::new(message)).await }
+    }).await;
+    let grpc_duration = grpc_start.elapsed().as_millis() as f64;
+    let response = match grpc_result{
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            CANCEL_ORDER_GRPC_DURATION.with_label_values(&["error"]).observe(grpc_duration);
+            ERROR_COUNTER.with_label_values(&["cancel_order", "backend_unavailable"]).inc();
+            return Json(CancelOrderRes{
+                order_id : req.order_id,
+                status : 503,
+                output : Some(format!("orderbook backend unavailable: {}", status.message()))
+            });
+        }
+    };
     let converted_res = CancelOrderRes::from(response);
+    CANCEL_ORDER_GRPC_DURATION.with_label_values(&[converted_res.status.to_string()]).observe(grpc_duration);
+    if converted_res.status != 200{
+        ERROR_COUNTER.with_label_values(&["cancel_order", "rejected"]).inc();
+    }
     let total_time = start_time.elapsed().as_millis() as f64;
     CANCEL_ORDER_TOTAL_DURATION.with_label_values(&[converted_res.status.to_string()]).observe(total_time);
     REQUEST_COUNTER.inc();
+    // A cancelled order is terminal, so drop its entry instead of growing
+    // `order_securities` for the life of the process.
+    let security_name = shared_state.order_securities.lock().unwrap().remove(&converted_res.order_id)
+        .unwrap_or_else(|| "unknown".to_string());
+    let _ = shared_state.order_events.send(OrderEvent{
+        event_kind : OrderEventKind::Cancel,
+        order_id : converted_res.order_id.clone(),
+        security_name : Some(security_name.clone()),
+        side : None,
+        delta_price : None,
+        delta_quantity : None,
+        resting_quantity : None,
+        status : converted_res.status
+    });
+    let _ = shared_state.fills.send(FillEvent{
+        order_id : converted_res.order_id.clone(),
+        security_name : Some(security_name.clone()),
+        side : None,
+        price : None,
+        quantity : None,
+        counterparty_order_index : None,
+        event_kind : OrderEventKind::Cancel,
+        timestamp_ms : FillEvent::now_ms()
+    });
+    publish_order_event(&shared_state, &security_name, "cancel", NatsOrderEvent{
+        order_id : converted_res.order_id.clone(),
+        side : None,
+        price : None,
+        quantity : None,
+        status : converted_res.status
+    });
     Json(converted_res)
 }
 
@@ -141,24 +761,39 @@ async fn depth(
     State(mut shared_state) : State<SharedState>,
     Json(request) : Json<DepthReq>
 ) -> Json<DepthRes>{
+    let _in_flight = InFlightGuard::new("depth");
     let start_time = Instant::now();
     let req = request;
     let security_name = req.security_name.clone();
-    let level_count = if req.level_count.unwrap() == 0{
-        None
-    } else {
-        req.level_count
-    };
-    let depth_request = Request::new(BookRequest{
+    let level_count = normalize_level_count(req.level_count);
+    let book_request = BookRequest{
         security_name : req.security_name,
         level_count
-    });
-    let response = shared_state.client.book_depth(depth_request).await.unwrap().into_inner();
+    };
+    let grpc_start = Instant::now();
+    let grpc_result = call_with_retry(|| {
+        let mut client = shared_state.client.clone();
+        let message = book_request.clone();
+        async move { client.book_depth(Request::new(message)).await }
+    }).await;
+    let grpc_duration = grpc_start.elapsed().as_millis() as f64;
+    let response = match grpc_result{
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            DEPTH_GRPC_DURATION.with_label_values(&[security_name, "error".to_string()]).observe(grpc_duration);
+            ERROR_COUNTER.with_label_values(&["depth", "backend_unavailable"]).inc();
+            return Json(DepthRes{
+                status : 503,
+                output : format!("orderbook backend unavailable: {}", status.message())
+            });
+        }
+    };
     let book_depth = response.book_depth;
     let total_time = start_time.elapsed().as_millis() as f64;
     REQUEST_COUNTER.inc();
     match book_depth{
         Some(book) => {
+            DEPTH_GRPC_DURATION.with_label_values(&[security_name.clone(), 200.to_string()]).observe(grpc_duration);
             DEPTH_TOTAL_DURATION.with_label_values(&[security_name, 200.to_string()]).observe(total_time);
             println!("-------ASK-------");
             for e in &book.ask_depth{
@@ -174,13 +809,114 @@ async fn depth(
             })
         }
         None => {
+            DEPTH_GRPC_DURATION.with_label_values(&[security_name.clone(), 400.to_string()]).observe(grpc_duration);
             DEPTH_TOTAL_DURATION.with_label_values(&[security_name, 400.to_string()]).observe(total_time);
+            ERROR_COUNTER.with_label_values(&["depth", "empty_book"]).inc();
             println!("book is empty");
             Json(DepthRes { status: 400, output: "orderbook is empty".to_string() })
         }
     }
 }
 
+async fn ws_handler(
+    ws : WebSocketUpgrade,
+    State(shared_state) : State<SharedState>
+) -> impl IntoResponse{
+    ws.on_upgrade(move |socket| stream_order_events(socket, shared_state))
+}
+
+async fn stream_order_events(mut socket : WebSocket, shared_state : SharedState){
+    let mut order_events = shared_state.order_events.subscribe();
+    while let Ok(event) = order_events.recv().await{
+        let Ok(payload) = serde_json::to_string(&event) else { continue };
+        if socket.send(Message::Text(payload)).await.is_err(){
+            break;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DepthStreamQuery{
+    pub security_name : String,
+    pub level_count : Option<u32>
+}
+
+async fn depth_stream_handler(
+    ws : WebSocketUpgrade,
+    State(shared_state) : State<SharedState>,
+    Query(query) : Query<DepthStreamQuery>
+) -> impl IntoResponse{
+    ws.on_upgrade(move |socket| stream_depth_diffs(socket, shared_state, query))
+}
+
+/// Consumes the backend's `stream_book_depth` push feed: an initial full snapshot
+/// tagged with its sequence number, then compact per-level diffs as the book changes.
+/// Diffs are run through a [`DepthReorderBuffer`] since the backend stream can reorder
+/// or drop updates in transit; a snapshot resets the buffer, and a diff that arrives
+/// before any snapshot is dropped (there's nothing to apply it against yet).
+async fn stream_depth_diffs(mut socket : WebSocket, mut shared_state : SharedState, query : DepthStreamQuery){
+    let book_request = BookRequest{
+        security_name : query.security_name.clone(),
+        level_count : normalize_level_count(query.level_count)
+    };
+    let grpc_result = call_with_retry(|| {
+        let mut client = shared_state.client.clone();
+        let message = book_request.clone();
+        async move { client.stream_book_depth(Request::new(message)).await }
+    }).await;
+    let Ok(response) = grpc_result else { return };
+    let mut backend_stream : Streaming<BookDepthUpdate> = response.into_inner();
+
+    let mut reorder_buffer : Option<DepthReorderBuffer> = None;
+    while let Ok(Some(update)) = backend_stream.message().await{
+        if update.is_snapshot{
+            let payload = DepthStreamMessage::Snapshot(DepthSnapshot{
+                sequence : update.sequence,
+                security_name : query.security_name.clone(),
+                ask_depth : update.ask_depth.iter().map(|level| DepthLevel{ price : level.price, quantity : level.quantity }).collect(),
+                bid_depth : update.bid_depth.iter().map(|level| DepthLevel{ price : level.price, quantity : level.quantity }).collect()
+            });
+            reorder_buffer = Some(DepthReorderBuffer::new(update.sequence));
+            let Ok(payload) = serde_json::to_string(&payload) else { continue };
+            if socket.send(Message::Text(payload)).await.is_err(){
+                return;
+            }
+            continue;
+        }
+
+        let Some(buffer) = reorder_buffer.as_mut() else { continue };
+        let diff = DepthDiff{
+            sequence : update.sequence,
+            side : if update.side == 0 {BookSide::Bid} else {BookSide::Ask},
+            price : update.price,
+            quantity : update.quantity,
+            removed : update.removed
+        };
+        match buffer.ingest(diff){
+            Some(ready) => {
+                for diff in ready{
+                    let Ok(payload) = serde_json::to_string(&DepthStreamMessage::Diff(diff)) else { continue };
+                    if socket.send(Message::Text(payload)).await.is_err(){
+                        return;
+                    }
+                }
+            }
+            None => {
+                // Gap never closed within the window; drop the buffer and re-request the
+                // stream so the backend starts over with a fresh snapshot.
+                reorder_buffer = None;
+                let grpc_result = call_with_retry(|| {
+                    let mut client = shared_state.client.clone();
+                    let message = book_request.clone();
+                    async move { client.stream_book_depth(Request::new(message)).await }
+                }).await;
+                let Ok(response) = grpc_result else { return };
+                backend_stream = response.into_inner();
+            }
+        }
+    }
+}
+
 async fn metric(State(shared_state): State<SharedState>) -> String{
     let metric_families = shared_state.registry.gather();
     let encoder = TextEncoder::new();
@@ -193,6 +929,54 @@ pub struct NewOrder{
     pub quantity : u32,
     pub is_buy_side : bool,
     pub security_name : String,
+    #[serde(default)]
+    pub order_type : NewOrderType,
+}
+
+/// Order types accepted on `/new`. Anything other than `Market` requires `price`
+/// to be set, since the backend has nothing to rest an unpriced order at.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewOrderType{
+    Market,
+    Limit,
+    Ioc,
+    Fok,
+    PostOnly
+}
+
+impl Default for NewOrderType{
+    fn default() -> Self{
+        NewOrderType::Market
+    }
+}
+
+impl NewOrderType{
+    fn requires_price(self) -> bool{
+        !matches!(self, NewOrderType::Market)
+    }
+
+    fn metric_label(self) -> &'static str{
+        match self{
+            NewOrderType::Market => "market",
+            NewOrderType::Limit => "limit",
+            NewOrderType::Ioc => "ioc",
+            NewOrderType::Fok => "fok",
+            NewOrderType::PostOnly => "post_only",
+        }
+    }
+}
+
+impl From<NewOrderType> for OrderType{
+    fn from(value : NewOrderType) -> Self{
+        match value{
+            NewOrderType::Market => OrderType::Market,
+            NewOrderType::Limit => OrderType::Limit,
+            NewOrderType::Ioc => OrderType::Ioc,
+            NewOrderType::Fok => OrderType::Fok,
+            NewOrderType::PostOnly => OrderType::PostOnly,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -259,3 +1043,100 @@ pub struct DepthRes{
     status : u32,
     output : String
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn scale_price_divides_by_hundred(){
+        assert_eq!(scale_price(12_345), 123.45);
+    }
+
+    #[test]
+    fn scale_price_does_not_apply_to_quantity(){
+        let raw_quantity = 50_u32;
+        assert_eq!(raw_quantity as f64, 50.0);
+        assert_ne!(raw_quantity as f64, scale_price(raw_quantity));
+    }
+
+    #[test]
+    fn is_retryable_is_true_only_for_transient_grpc_codes(){
+        assert!(is_retryable(&Status::new(Code::Unavailable, "down")));
+        assert!(is_retryable(&Status::new(Code::DeadlineExceeded, "timeout")));
+        assert!(is_retryable(&Status::new(Code::Aborted, "aborted")));
+        assert!(!is_retryable(&Status::new(Code::InvalidArgument, "bad request")));
+        assert!(!is_retryable(&Status::new(Code::NotFound, "missing")));
+    }
+
+    #[tokio::test]
+    async fn call_with_retry_gives_up_after_max_attempts_on_transient_errors(){
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result : Result<(), Status> = call_with_retry(|| {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { Err(Status::new(Code::Unavailable, "still down")) }
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), MAX_GRPC_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn call_with_retry_does_not_retry_non_transient_errors(){
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result : Result<(), Status> = call_with_retry(|| {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { Err(Status::new(Code::InvalidArgument, "bad request")) }
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    fn test_diff(sequence : u64) -> DepthDiff{
+        DepthDiff{ sequence, side : BookSide::Bid, price : 100, quantity : 1, removed : false }
+    }
+
+    #[test]
+    fn reorder_buffer_applies_in_order_diffs_immediately(){
+        let mut buffer = DepthReorderBuffer::new(0);
+        let ready = buffer.ingest(test_diff(1)).expect("no gap yet");
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].sequence, 1);
+    }
+
+    #[test]
+    fn reorder_buffer_holds_out_of_order_diffs_until_the_gap_fills(){
+        let mut buffer = DepthReorderBuffer::new(0);
+
+        let ready = buffer.ingest(test_diff(2)).expect("within the reorder window");
+        assert!(ready.is_empty(), "sequence 2 must wait for sequence 1");
+
+        let ready = buffer.ingest(test_diff(1)).expect("within the reorder window");
+        let applied : Vec<u64> = ready.iter().map(|d| d.sequence).collect();
+        assert_eq!(applied, vec![1, 2], "filling the gap should flush both in order");
+    }
+
+    #[test]
+    fn reorder_buffer_drops_duplicate_or_stale_sequences(){
+        let mut buffer = DepthReorderBuffer::new(5);
+        let ready = buffer.ingest(test_diff(5)).expect("already-applied sequence is a no-op");
+        assert!(ready.is_empty());
+    }
+
+    #[test]
+    fn only_market_orders_skip_the_price_requirement(){
+        assert!(!NewOrderType::Market.requires_price());
+        assert!(NewOrderType::Limit.requires_price());
+        assert!(NewOrderType::Ioc.requires_price());
+        assert!(NewOrderType::Fok.requires_price());
+        assert!(NewOrderType::PostOnly.requires_price());
+    }
+
+    #[test]
+    fn new_order_type_maps_onto_the_matching_proto_order_type(){
+        assert_eq!(OrderType::from(NewOrderType::Market), OrderType::Market);
+        assert_eq!(OrderType::from(NewOrderType::Limit), OrderType::Limit);
+        assert_eq!(OrderType::from(NewOrderType::Ioc), OrderType::Ioc);
+        assert_eq!(OrderType::from(NewOrderType::Fok), OrderType::Fok);
+        assert_eq!(OrderType::from(NewOrderType::PostOnly), OrderType::PostOnly);
+    }
+}